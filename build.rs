@@ -0,0 +1,26 @@
+//! Embed the git commit and build date in the version shown by `aocprep --version`
+
+use std::process::Command;
+
+fn main() {
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    // SOURCE_DATE_EPOCH keeps reproducible builds reproducible
+    let date = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .and_then(|epoch| chrono::DateTime::from_timestamp(epoch, 0))
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%d");
+    println!("cargo:rustc-env=AOCPREP_GIT_SHA={}", sha);
+    println!("cargo:rustc-env=AOCPREP_BUILD_DATE={}", date);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
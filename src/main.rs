@@ -1,227 +1,408 @@
-use anyhow::{Context, Result};
-use itertools::Itertools;
-use reqwest;
-use scraper::{Html, Selector};
-use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::{Path, PathBuf};
+use anyhow::Result;
+use aocprep::{
+    archive, check_config_option, check_session, check_tests, clean, copy_skeleton,
+    current_aoc_day, error_hint, fetch_day, fetch_days, fetch_url, get_leaderboard, get_tests,
+    init, parse_duration, print_input, print_unlock, project_folder, status, submit, RunContext,
+    RunOptions, RunSummary,
+};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
-use toml;
 
-#[derive(Deserialize, Serialize, Debug)]
-struct Config {
-    year: String,
-    session: String,
-}
+/// Version with the git commit and date of the build, see build.rs
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("AOCPREP_GIT_SHA"),
+    " ",
+    env!("AOCPREP_BUILD_DATE"),
+    ")"
+);
 
-struct RunContext {
-    day_name: String,
-    base_folder: PathBuf,
-}
+/// An advent of code skeleton tool
+///
+/// Run in project folder with day folder name as argument to copy skeleton
+/// Run from within day folder without argument to download inputs
+#[derive(StructOpt, Debug)]
+#[structopt(version = VERSION)]
+struct Opt {
+    /// Day name for a new day folder, same as `new <day-name>`. Format should be "day##"
+    day_name: Option<String>,
 
-impl RunContext {
-    fn day_number(&self) -> Result<usize> {
-        self.day_name[3..]
-            .parse()
-            .with_context(|| format!("Unable to parse day number from {}", self.day_name))
-    }
+    /// Do not use cached pages, e.g. to pick up part two after it unlocks
+    #[structopt(long, global = true)]
+    no_cache: bool,
 
-    fn day_folder(&self) -> PathBuf {
-        self.base_folder.join(&self.day_name)
-    }
+    /// Only use cached pages, never access the network
+    #[structopt(long, global = true, conflicts_with = "no-cache")]
+    offline: bool,
 
-    fn aoc_config(&self) -> Result<Config> {
-        let config_file = self.base_folder.join("aoc.toml");
-        fs::read_to_string(&config_file)
-            .with_context(|| format!("Error reading config file {:?}", &config_file))
-            .and_then(|s| toml::from_str::<Config>(&s).context("Parsing config file"))
-    }
-}
+    /// Show what would be copied, written and fetched without doing it
+    #[structopt(long, global = true)]
+    dry_run: bool,
 
-fn retrieve_aoc(config: &Config, day_number: usize, postfix: &str) -> Result<String> {
-    let url = format!(
-        "https://adventofcode.com/{}/day/{}{}",
-        config.year, day_number, postfix
-    );
-    let client = reqwest::blocking::Client::new();
-    Ok(client
-        .get(&url)
-        .header("Cookie", format!("session={}", config.session))
-        // https://old.reddit.com/r/adventofcode/comments/z9dhtd/please_include_your_contact_info_in_the_useragent/
-        .header(
-            "User-Agent",
-            "https://github.com/Japanuspus/aocprep by janus@insignificancegalore.net",
-        )
-        .send()?
-        .error_for_status()
-        .context("Input not available (too soon?)")?
-        .text()?)
-}
+    /// Give up on downloads after this time, e.g. "60s" or "5m", including retries
+    #[structopt(long, global = true, parse(try_from_str = parse_duration))]
+    deadline: Option<Duration>,
 
-fn get_inputs(run: &RunContext) -> Result<()> {
-    let input_file = run.day_folder().join("input.txt");
+    /// Number of times to retry a request on connection or server errors, defaults to 3
+    #[structopt(long, global = true)]
+    retries: Option<usize>,
 
-    if input_file.exists() {
-        println!("Input file {:?} exists, not retrieving", &input_file);
-        return Ok(());
-    }
+    /// Timeout for each request in seconds, overriding timeout_secs in aoc.toml
+    #[structopt(long, global = true)]
+    timeout: Option<u64>,
 
-    let input = retrieve_aoc(&run.aoc_config()?, run.day_number()?, "/input")?;
-    fs::write(&input_file, input)?;
+    /// Day to fetch, instead of the day of the folder we are in
+    #[structopt(long, global = true)]
+    day: Option<usize>,
 
-    Ok(())
+    /// Puzzle year, overriding the year in aoc.toml
+    #[structopt(long, global = true)]
+    year: Option<String>,
+
+    /// Use the session of this [profiles.NAME] in aoc.toml. The input is saved as e.g. input-NAME.txt
+    #[structopt(long, global = true)]
+    profile: Option<String>,
+
+    /// Project folder, instead of the closest folder with aoc.toml
+    #[structopt(long, global = true, parse(from_os_str))]
+    base: Option<PathBuf>,
+
+    /// Config file to use instead of aoc.toml in the project folder
+    #[structopt(long, global = true, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// If the puzzle is not unlocked yet, wait for it instead of failing
+    #[structopt(long, global = true)]
+    wait: bool,
+
+    /// Overwrite existing input and test files, and submit answers already found to be wrong
+    #[structopt(long, global = true)]
+    force: bool,
+
+    /// Choose interactively which example blocks to save as tests
+    #[structopt(long, global = true)]
+    pick: bool,
+
+    /// Name test files by puzzle part, like test-p2-00.txt
+    #[structopt(long, global = true)]
+    part_labels: bool,
+
+    /// Open the puzzle in the browser after downloading
+    #[structopt(long, global = true)]
+    open: bool,
+
+    /// Fail instead of warning when the input is suspiciously short
+    #[structopt(long, global = true)]
+    strict: bool,
+
+    /// If the input file exists, report whether it differs from the input on the server
+    #[structopt(long, global = true)]
+    check: bool,
+
+    /// Save all example blocks as tests, not only those introduced by "For example"
+    #[structopt(long, global = true)]
+    all_blocks: bool,
+
+    /// Also write all tests to tests.md, to look through them together
+    #[structopt(long, global = true)]
+    combine_tests: bool,
+
+    /// Only fetch the input, skipping the puzzle page with the tests
+    #[structopt(long, global = true)]
+    no_tests: bool,
+
+    /// Only fetch the tests and puzzle description, skipping the input
+    #[structopt(long, global = true)]
+    no_input: bool,
+
+    /// Copy skeletons/TEMPLATE instead of the skeleton folder to new day folders
+    #[structopt(long, global = true)]
+    template: Option<String>,
+
+    /// Copy the input to the clipboard after downloading. On Linux, aocprep waits up to 10s for it
+    /// to be pasted, as the clipboard is lost on exit unless a clipboard manager is running
+    #[structopt(long, global = true)]
+    clipboard: bool,
+
+    /// Print a JSON summary of what was done instead of progress messages
+    #[structopt(long, global = true)]
+    json: bool,
+
+    /// When not run from a day folder, use today's puzzle
+    #[structopt(long, global = true)]
+    auto: bool,
+
+    /// Also show the urls being fetched
+    #[structopt(short, long, global = true)]
+    verbose: bool,
+
+    /// Only show errors
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Defaults to `new` with a day name and to `fetch` without
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
 }
 
-fn expand_cargo_toml(run: &RunContext, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
-    println!("Expanding Cargo.toml with day name");
-    let mut cargo: toml::Value = fs::read_to_string(src)
-        .context("Unable to read skeleton/Cargo.toml")?
-        .parse()
-        .context("While reading skeleton/Cargo.toml")?;
-
-    cargo
-        .get_mut("package")
-        .unwrap()
-        .as_table_mut()
-        .unwrap()
-        .insert(
-            "name".to_string(),
-            toml::Value::String(run.day_name.clone()),
-        );
-
-    fs::write(dst, cargo.to_string())?;
-    Ok(())
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Copy skeleton to a new day folder. Run in project folder
+    New {
+        /// Day name. Format should be "day##"
+        day_name: String,
+    },
+    /// Download input, tests and puzzle description. Run from within day folder
+    Fetch,
+    /// Print the input to stdout, e.g. `aocprep input | cargo run`. Run from within day folder
+    Input,
+    /// Download the example tests again, e.g. after part two unlocks. Run from within day folder
+    Tests,
+    /// Submit an answer. Run from within day folder, or use --day
+    Submit {
+        /// Puzzle part, 1 or 2
+        part: usize,
+        answer: String,
+    },
+    /// Copy skeleton and download inputs for several days. Run in project folder
+    Days {
+        /// Day numbers, e.g. "1-5" or "1,3,7"
+        days: String,
+    },
+    /// Download all unlocked days of the year, skipping days already downloaded. Run in project folder
+    Archive,
+    /// Remove downloaded input, tests and puzzle description from a day folder
+    Clean {
+        /// Day name. Defaults to --day, or else the day folder we are in
+        day_name: Option<String>,
+        /// Do not ask for confirmation
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Download a private leaderboard as json. Run in project folder
+    Leaderboard {
+        /// Leaderboard id, as in the url of the leaderboard
+        id: String,
+    },
+    /// Show downloaded files and stars for each day folder. Run in project folder
+    Status,
+    /// Run the solution on the tests and compare with the expected answers. Run from within day folder
+    Check,
+    /// Print a page of the advent of code server, fetched with the session. For looking into scraping issues
+    FetchUrl {
+        /// Url or path on the server, e.g. "/2022/day/5"
+        url: String,
+        /// Write the page to this file instead of stdout
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Show when a puzzle unlocks, in EST and local time
+    Unlock {
+        /// Day number. Defaults to --day, the day folder we are in, or else today
+        day_number: Option<usize>,
+    },
+    /// Show the name of the user logged in with the configured session
+    Whoami,
+    /// Create aoc.toml, skeleton and workspace in the current folder
+    Init {
+        /// Session cookie. Prompted for if not given
+        #[structopt(long)]
+        session: Option<String>,
+    },
+    /// Write a shell completion script to stdout
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    Completions {
+        /// One of bash, zsh, fish, powershell or elvish
+        #[structopt(possible_values = &structopt::clap::Shell::variants(), case_insensitive = true)]
+        shell: structopt::clap::Shell,
+    },
 }
 
-fn copy_dir_recursive(
-    run: &RunContext,
-    src: impl AsRef<Path>,
-    dst: impl AsRef<Path>,
-    do_expand_cargo_toml: bool,
-) -> Result<()> {
-    fs::create_dir_all(&dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        if ty.is_dir() {
-            copy_dir_recursive(
-                run,
-                entry.path(),
-                dst.as_ref().join(entry.file_name()),
-                false,
-            )?;
-        } else if do_expand_cargo_toml && entry.file_name().eq("Cargo.toml") {
-            expand_cargo_toml(run, entry.path(), dst.as_ref().join(entry.file_name()))?;
-        } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        }
+#[test]
+fn test_day_name_argument() {
+    for day_name in ["day05", "day", "da"] {
+        let opt = Opt::from_iter_safe(["aocprep", day_name]).unwrap();
+        assert_eq!(opt.day_name.as_deref(), Some(day_name));
+        assert!(opt.cmd.is_none());
     }
-    Ok(())
+    let opt = Opt::from_iter_safe(["aocprep", "days", "1-3"]).unwrap();
+    assert!(matches!(opt.cmd, Some(Command::Days { .. })));
 }
 
-fn copy_skeleton(run: &RunContext) -> Result<()> {
-    let day_folder = run.day_folder();
-    let skeleton_folder = run.base_folder.join("skeleton");
-
-    if day_folder.exists() {
-        println!("Day folder exists, not copying skeleton");
-    } else {
-        println!(
-            "No day folder exists for {}, will copy skeleton with Cargo.toml expansion",
-            &run.day_name
-        );
-        copy_dir_recursive(run, skeleton_folder, day_folder, true)?;
+/// Options for the library, from the command line
+fn run_options(opt: &Opt, progress: indicatif::MultiProgress) -> RunOptions {
+    RunOptions {
+        no_cache: opt.no_cache,
+        offline: opt.offline,
+        dry_run: opt.dry_run,
+        deadline: opt.deadline,
+        retries: opt.retries,
+        timeout: opt.timeout,
+        day: opt.day,
+        year: opt.year.clone(),
+        profile: opt.profile.clone(),
+        base: opt.base.clone(),
+        config: opt.config.clone(),
+        wait: opt.wait,
+        force: opt.force,
+        pick: opt.pick,
+        part_labels: opt.part_labels,
+        open: opt.open,
+        strict: opt.strict,
+        check: opt.check,
+        all_blocks: opt.all_blocks,
+        combine_tests: opt.combine_tests,
+        no_tests: opt.no_tests,
+        no_input: opt.no_input,
+        template: opt.template.clone(),
+        clipboard: opt.clipboard,
+        progress,
     }
+}
 
+/// Print what the runs did as JSON if requested with --json
+fn print_summary(opt: &Opt, summaries: Vec<RunSummary>) -> Result<()> {
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+    }
     Ok(())
 }
 
-fn parse_tests(html: &str) -> Result<Vec<String>> {
-    let document = Html::parse_document(html);
-    let selector = Selector::parse("pre>code").unwrap();
-    let tests = document
-        .select(&selector)
-        .map(|el| el.text().join(""))
-        .collect();
-    Ok(tests)
+/// Writes log lines to stderr, hiding the progress bars while writing so they are redrawn below
+struct LogWriter(indicatif::MultiProgress);
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.suspend(|| std::io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
 }
 
-#[test]
-fn test_parse_tests() {
-    let html = r##"<!DOCTYPE html>
-    <html lang="en-us">
-    <head>
-    <meta charset="utf-8"/>
-    <title>Day 7 - Advent of Code 2021</title>
-    <link rel="shortcut icon" href="/favicon.png"/>
-    </head><!--
-    Oh, hello!  Funny seeing you here.
-    -->
-    <body>
-    <p>For example, consider the following horizontal positions:</p>
-    <pre><code>16,1,2,0,4,2,7,1,2,14</code></pre>
-    <p>This means there's a crab with horizontal position <code>16</code>, a crab with horizontal position <code>1</code>, and so on.</p>
-    </body>
-    </html>
-    "##;
-    let v = parse_tests(&html).unwrap();
-    assert!(v.len() == 1);
-    assert!(v[0] == "16,1,2,0,4,2,7,1,2,14");
+fn init_logging(opt: &Opt, progress: indicatif::MultiProgress) {
+    let level = if opt.quiet {
+        log::LevelFilter::Error
+    } else if opt.verbose {
+        log::LevelFilter::Debug
+    } else if opt.json {
+        log::LevelFilter::Warn
+    } else {
+        log::LevelFilter::Info
+    };
+    env_logger::Builder::new()
+        .filter_module(module_path!(), level)
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .target(env_logger::Target::Pipe(Box::new(LogWriter(progress))))
+        .init();
 }
 
-fn get_tests(run: &RunContext) -> Result<()> {
-    let html = retrieve_aoc(&run.aoc_config()?, run.day_number()?, "")?;
-    let tests = parse_tests(&html)?;
-
-    for (i, s) in tests.iter().enumerate() {
-        let dst = run.day_folder().join(format!("test{:02}.txt", i));
-        if dst.exists() {
-            println!("Test file {:?} exists", dst);
-        } else {
-            println!("Writing test file {:?}", dst);
-            fs::write(&dst, s)?;
+fn main() {
+    if let Err(e) = try_main() {
+        eprintln!("Error: {:?}", e);
+        if let Some(hint) = error_hint(&e) {
+            eprintln!("Hint: {}", hint);
         }
+        std::process::exit(1);
     }
-    Ok(())
 }
 
-/// An advent of code skeleton tool
-///
-/// Run in project folder with day folder name as argument to copy skeleton
-/// Run from within day folder without argument to download inputs
-#[derive(StructOpt, Debug)]
-struct Opt {
-    /// Day name. Format should be "day##"
-    day_name: Option<String>,
-}
-
-fn main() -> Result<()> {
-    let opt = Opt::from_args();
-    if let Some(day_name) = opt.day_name {
-        let run = RunContext {
-            day_name,
-            base_folder: std::env::current_dir()?,
-        };
-        copy_skeleton(&run)
+fn try_main() -> Result<()> {
+    let mut opt = Opt::from_args();
+    let progress = if opt.quiet || opt.json {
+        indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden())
     } else {
-        let current_folder = std::env::current_dir()?;
-        let base_folder = current_folder
-            .parent()
-            .expect("No parent folder")
-            .to_owned();
-        let day_name = current_folder
-            .file_name()
-            .unwrap()
-            .to_str()
-            .expect("Invalid folder name")
-            .to_owned();
-        let run = RunContext {
-            base_folder,
-            day_name,
-        };
-        run.aoc_config()?;
-        get_inputs(&run)?;
-        get_tests(&run)?;
-        Ok(())
+        indicatif::MultiProgress::new()
+    };
+    init_logging(&opt, progress.clone());
+    let mut options = run_options(&opt, progress);
+    check_config_option(&options)?;
+    let cmd = match (opt.cmd.take(), opt.day_name.take()) {
+        (Some(cmd), _) => cmd,
+        (None, Some(day_name)) => Command::New { day_name },
+        (None, None) => Command::Fetch,
+    };
+    match cmd {
+        Command::New { day_name } => {
+            // Projects without aoc.toml can still use the skeleton
+            let base_folder = project_folder(&options).or_else(|_| std::env::current_dir())?;
+            let run = RunContext::new(day_name, base_folder, &options);
+            copy_skeleton(&run)?;
+            print_summary(&opt, vec![run.into_summary()])
+        }
+        Command::Fetch => {
+            let mut run = RunContext::for_day(&options)?;
+            if opt.auto && run.day_number().is_err() {
+                run = RunContext::new(
+                    format!("day{:02}", current_aoc_day()?),
+                    run.base_folder,
+                    &options,
+                );
+                copy_skeleton(&run)?;
+            }
+            fetch_day(&run)?;
+            print_summary(&opt, vec![run.into_summary()])
+        }
+        Command::Input => print_input(&RunContext::for_day(&options)?),
+        Command::Tests => {
+            let mut run = RunContext::for_day(&options)?;
+            // The cached page may be from before part two unlocked
+            run.refresh_cache = true;
+            get_tests(&run)
+        }
+        Command::Submit { part, answer } => {
+            let run = RunContext::for_day(&options)?;
+            submit(&run, part, &answer)
+        }
+        Command::Days { days } => print_summary(&opt, fetch_days(&options, &days)?),
+        Command::Archive => print_summary(&opt, archive(&options)?),
+        Command::Clean { day_name, yes } => {
+            let run = match day_name {
+                Some(day_name) => RunContext::new(day_name, project_folder(&options)?, &options),
+                None => RunContext::for_day(&options)?,
+            };
+            clean(&run, yes)
+        }
+        Command::Leaderboard { id } => get_leaderboard(
+            &RunContext::for_project(project_folder(&options)?, &options),
+            &id,
+        ),
+        Command::Status => status(&options, &project_folder(&options)?),
+        Command::Check => check_tests(&RunContext::for_day(&options)?),
+        Command::FetchUrl { url, output } => fetch_url(
+            &RunContext::for_project(project_folder(&options)?, &options),
+            &url,
+            output.as_deref(),
+        ),
+        Command::Unlock { day_number } => {
+            // Only the config is needed, not the session
+            options.offline = true;
+            let run = RunContext::for_day(&options).or_else(|_| {
+                project_folder(&options).map(|base| RunContext::for_project(base, &options))
+            })?;
+            print_unlock(&run, day_number)
+        }
+        Command::Whoami => {
+            let name = check_session(&RunContext::for_project(
+                project_folder(&options)?,
+                &options,
+            ))?;
+            println!("{}", name);
+            Ok(())
+        }
+        Command::Init { session } => init(
+            &std::env::current_dir()?,
+            opt.year.as_deref(),
+            session.as_deref(),
+            opt.dry_run,
+        ),
+        Command::Completions { shell } => {
+            Opt::clap().gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut std::io::stdout());
+            Ok(())
+        }
     }
 }